@@ -0,0 +1,42 @@
+use crate::Language;
+
+/// How to launch a language server: the binary to run, its arguments, and
+/// any extra environment variables it needs. Passed to `LSPServer::new` so
+/// callers aren't limited to whatever binary name we happen to guess.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+}
+
+impl ServerConfig {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            args: Vec::new(),
+            env: Vec::new(),
+        }
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+}
+
+/// The command used for each `Language` when the caller doesn't have a
+/// preference of their own. `rust-analyzer` and `pylsp` are used in place of
+/// the long-abandoned `rls`/`pyls`.
+pub fn default_server_config(language: &Language) -> ServerConfig {
+    match language {
+        Language::C | Language::Cpp => ServerConfig::new("clangd"),
+        Language::Rust => ServerConfig::new("rust-analyzer"),
+        Language::Python => ServerConfig::new("pylsp"),
+    }
+}
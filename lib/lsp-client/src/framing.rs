@@ -0,0 +1,134 @@
+use {
+    crate::LSPError,
+    serde::de::DeserializeOwned,
+    std::io::{BufRead, Read},
+};
+
+const CONTENT_LENGTH: &str = "content-length";
+
+/// Reads one LSP base-protocol message (headers + JSON body) from `reader` and
+/// deserializes the body into `T`.
+///
+/// The base protocol frames every message as a set of `Header: value\r\n`
+/// lines terminated by a blank line, followed by exactly `Content-Length`
+/// bytes of UTF-8 encoded JSON. Headers other than `Content-Length` (e.g.
+/// `Content-Type`) are accepted and ignored.
+pub fn read_message<R, T>(reader: &mut R) -> Result<T, LSPError>
+where
+    R: BufRead,
+    T: DeserializeOwned,
+{
+    let body = read_message_bytes(reader)?;
+    serde_json::from_slice(&body).map_err(LSPError::JSONSerializationError)
+}
+
+/// Reads one LSP base-protocol message and returns its raw JSON body.
+pub fn read_message_bytes<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, LSPError> {
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut line = String::new();
+        let n = reader
+            .read_line(&mut line)
+            .map_err(|e| LSPError::Protocol(format!("failed to read header line: {}", e)))?;
+        if n == 0 {
+            return Err(LSPError::Protocol(
+                "unexpected end of stream while reading headers".to_owned(),
+            ));
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| LSPError::Protocol(format!("malformed header: {:?}", line)))?;
+        if name.trim().to_ascii_lowercase() == CONTENT_LENGTH {
+            let value = value.trim();
+            content_length =
+                Some(value.parse().map_err(|_| {
+                    LSPError::Protocol(format!("invalid Content-Length: {:?}", value))
+                })?);
+        }
+        // Unknown headers (e.g. Content-Type) are ignored.
+    }
+
+    let content_length = content_length
+        .ok_or_else(|| LSPError::Protocol("missing Content-Length header".to_owned()))?;
+
+    let mut body = vec![0u8; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|e| LSPError::Protocol(format!("truncated message body: {}", e)))?;
+    Ok(body)
+}
+
+/// Splits an already-framed, in-memory message (headers + body, as produced
+/// by a single write) into its JSON body, without needing a `BufRead`.
+pub fn split_frame(frame: &[u8]) -> Result<&[u8], LSPError> {
+    frame
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|idx| &frame[idx + 4..])
+        .ok_or_else(|| LSPError::Protocol("frame missing header terminator".to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_message_bytes_roundtrip() {
+        let frame = b"Content-Length: 13\r\n\r\n{\"foo\":\"bar\"}";
+        let body = read_message_bytes(&mut &frame[..]).unwrap();
+        assert_eq!(body, b"{\"foo\":\"bar\"}");
+    }
+
+    #[test]
+    fn test_read_message_bytes_case_insensitive_header() {
+        let frame = b"content-LENGTH: 13\r\n\r\n{\"foo\":\"bar\"}";
+        let body = read_message_bytes(&mut &frame[..]).unwrap();
+        assert_eq!(body, b"{\"foo\":\"bar\"}");
+    }
+
+    #[test]
+    fn test_read_message_bytes_ignores_content_type() {
+        let frame = b"Content-Type: application/vscode-jsonrpc\r\nContent-Length: 13\r\n\r\n{\"foo\":\"bar\"}";
+        let body = read_message_bytes(&mut &frame[..]).unwrap();
+        assert_eq!(body, b"{\"foo\":\"bar\"}");
+    }
+
+    #[test]
+    fn test_read_message_bytes_missing_content_length() {
+        let frame = b"Content-Type: application/vscode-jsonrpc\r\n\r\n{\"foo\":\"bar\"}";
+        let err = read_message_bytes(&mut &frame[..]).unwrap_err();
+        assert!(matches!(err, LSPError::Protocol(_)));
+    }
+
+    #[test]
+    fn test_read_message_bytes_truncated_body() {
+        let frame = b"Content-Length: 100\r\n\r\n{\"foo\":\"bar\"}";
+        let err = read_message_bytes(&mut &frame[..]).unwrap_err();
+        assert!(matches!(err, LSPError::Protocol(_)));
+    }
+
+    #[test]
+    fn test_read_message_deserializes_typed_body() {
+        let frame = b"Content-Length: 13\r\n\r\n{\"foo\":\"bar\"}";
+        let body: std::collections::HashMap<String, String> =
+            read_message(&mut &frame[..]).unwrap();
+        assert_eq!(body.get("foo").map(String::as_str), Some("bar"));
+    }
+
+    #[test]
+    fn test_split_frame_roundtrip() {
+        let frame = b"Content-Length: 13\r\n\r\n{\"foo\":\"bar\"}";
+        assert_eq!(split_frame(frame).unwrap(), b"{\"foo\":\"bar\"}");
+    }
+
+    #[test]
+    fn test_split_frame_missing_terminator() {
+        let frame = b"Content-Length: 13\r\n{\"foo\":\"bar\"}";
+        let err = split_frame(frame).unwrap_err();
+        assert!(matches!(err, LSPError::Protocol(_)));
+    }
+}
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// The error code JSON-RPC reserves for "no handler registered for this
+/// method", used when we answer a server-initiated call we don't support.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// A JSON-RPC id, which per spec may be either a number or a string.
+/// `Transport` always mints numeric ids for its own outgoing requests, but
+/// servers may send string ids on calls they initiate, so the wire type has
+/// to accept both rather than forcing every frame through a `u64`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(u64),
+    String(String),
+}
+
+impl Id {
+    /// The numeric id if this is one, for correlating against
+    /// `Transport`'s own `u64` request ids.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Id::Number(n) => Some(*n),
+            Id::String(_) => None,
+        }
+    }
+}
+
+impl From<u64> for Id {
+    fn from(id: u64) -> Self {
+        Id::Number(id)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Request<T: Serialize> {
+    jsonrpc: &'static str,
+    pub id: u64,
+    pub method: &'static str,
+    pub params: T,
+}
+
+impl<T: Serialize> Request<T> {
+    pub fn new(id: u64, method: &'static str, params: T) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            method,
+            params,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Notification<T: Serialize> {
+    jsonrpc: &'static str,
+    pub method: &'static str,
+    pub params: T,
+}
+
+impl<T: Serialize> Notification<T> {
+    pub fn new(method: &'static str, params: T) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            method,
+            params,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Response {
+    jsonrpc: &'static str,
+    pub id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResponseError>,
+}
+
+impl Response {
+    pub fn error(id: Id, code: i64, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            result: None,
+            error: Some(ResponseError {
+                code,
+                message: message.into(),
+            }),
+        }
+    }
+}
+
+/// A frame read off the wire, before we know whether it's a response to one
+/// of our requests, a notification, or a server-initiated call.
+#[derive(Debug, Deserialize)]
+pub struct Incoming {
+    pub id: Option<Id>,
+    pub method: Option<String>,
+    #[serde(default)]
+    pub params: Option<Value>,
+    #[serde(default)]
+    pub result: Option<Value>,
+    #[serde(default)]
+    pub error: Option<IncomingError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IncomingError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default)]
+    pub data: Option<Value>,
+}
+
+pub enum Shape {
+    /// A response to a request we sent, correlated by `id`.
+    Response {
+        id: Id,
+        result: Option<Value>,
+        error: Option<IncomingError>,
+    },
+    /// An unsolicited notification from the server (no `id`).
+    Notification {
+        method: String,
+        params: Option<Value>,
+    },
+    /// A server-initiated request we're expected to answer (has both `id`
+    /// and `method`).
+    Call {
+        id: Id,
+        method: String,
+        params: Option<Value>,
+    },
+}
+
+impl Incoming {
+    /// Classifies this frame per the JSON-RPC base protocol: requests and
+    /// calls carry `method`, responses don't; requests and calls carry `id`
+    /// to be answered, notifications don't.
+    pub fn classify(self) -> Shape {
+        match (self.id, self.method) {
+            (Some(id), Some(method)) => Shape::Call {
+                id,
+                method,
+                params: self.params,
+            },
+            (Some(id), None) => Shape::Response {
+                id,
+                result: self.result,
+                error: self.error,
+            },
+            (_, Some(method)) => Shape::Notification {
+                method,
+                params: self.params,
+            },
+            (None, None) => Shape::Notification {
+                method: String::new(),
+                params: self.params,
+            },
+        }
+    }
+}
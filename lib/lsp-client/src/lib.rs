@@ -1,18 +1,40 @@
 #[macro_use]
 extern crate log;
+mod config;
+mod framing;
+mod jsonrpc;
+mod messages;
+mod remote;
+mod transport;
+
+pub use crate::config::{default_server_config, ServerConfig};
+pub use crate::messages::ServerMessage;
+pub use crate::remote::UriMap;
+
 use {
-    lsp_types::{ClientCapabilities, InitializeParams, InitializeResult},
+    crate::remote::{RewritingReader, RewritingWriter},
+    crate::transport::Transport,
+    lsp_types::{
+        ClientCapabilities, InitializeParams, InitializeResult, InitializedParams,
+        ServerCapabilities,
+    },
     std::error::Error,
     std::fmt,
     std::io,
-    std::io::{BufRead, BufReader, Read, Write},
+    std::io::{BufReader, Read, Write},
     std::ops::Add,
     std::path::PathBuf,
-    std::process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    std::process::{Child, Command, Stdio},
+    std::thread,
+    std::time::{Duration, Instant},
 };
 
 type LSPResult = Result<(), LSPError>;
 
+/// How long `shutdown()` waits for the server to exit on its own after the
+/// `exit` notification before falling back to killing the process.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
 pub enum Language {
     Cpp,
     C,
@@ -20,21 +42,14 @@ pub enum Language {
     Rust,
 }
 
-impl Language {
-    fn program(&self) -> &str {
-        match self {
-            Self::C | Self::Cpp => "clangd",
-            Self::Rust => "rls",
-            Self::Python => "pyls",
-        }
-    }
-}
-
 struct LSPServer {
     language: Language,
     workspace: String,
+    config: ServerConfig,
     started: bool,
     process: Option<Child>,
+    transport: Option<Transport>,
+    capabilities: Option<ServerCapabilities>,
 }
 
 #[derive(Debug)]
@@ -61,6 +76,10 @@ enum LSPError {
     JSONSerializationError(serde_json::Error),
     NotRunning,
     InvalidProcess,
+    /// The exchange with the server failed: the peer violated the LSP base
+    /// protocol (malformed/missing headers, truncated body, ...), a message
+    /// could not be written, or the server answered a request with an error.
+    Protocol(String),
     Other(&'static str),
 }
 
@@ -92,6 +111,7 @@ impl fmt::Display for LSPError {
             Self::NotRunning => write!(f, "LSP is not running"),
             Self::InvalidProcess => write!(f, "Process is not initialized"),
             Self::JSONSerializationError(..) => write!(f, "Failed to serialize type to JSON"),
+            Self::Protocol(s) => write!(f, "LSP protocol error: {}", s),
             Self::Other(s) => write!(f, "Error in LSPServer: {}", &s),
         }
     }
@@ -156,12 +176,45 @@ impl LSPProtocol {
 }
 
 impl LSPServer {
-    fn new(language: Language, workspace: String) -> Self {
+    fn new(language: Language, workspace: String, config: ServerConfig) -> Self {
         Self {
             language,
             workspace,
+            config,
             started: false,
             process: None,
+            transport: None,
+            capabilities: None,
+        }
+    }
+
+    /// Runs the language server over an arbitrary `Read + Write` pipe
+    /// instead of a locally spawned `Child` (e.g. an SSH session to a
+    /// remote host), rewriting `file://` URIs crossing the wire per
+    /// `uri_map`. Since there's no local child to reap, `stop()`/`shutdown()`
+    /// only tear down the transport; the caller owns the pipe's lifecycle.
+    fn with_transport<R, W>(
+        language: Language,
+        workspace: String,
+        config: ServerConfig,
+        reader: R,
+        writer: W,
+        uri_map: UriMap,
+    ) -> Self
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let reader = RewritingReader::new(reader, uri_map.clone());
+        let writer = RewritingWriter::new(writer, uri_map);
+        Self {
+            language,
+            workspace,
+            config,
+            started: true,
+            process: None,
+            transport: Some(Transport::new(BufReader::new(reader), writer)),
+            capabilities: None,
         }
     }
 
@@ -172,36 +225,43 @@ impl LSPServer {
     fn process_mut(&mut self) -> Result<&mut Child, LSPError> {
         self.process.as_mut().ok_or(LSPError::InvalidProcess)
     }
+
+    fn transport(&self) -> Result<&Transport, LSPError> {
+        self.transport.as_ref().ok_or(LSPError::InvalidProcess)
+    }
+
+    /// Capabilities the server advertised in its `InitializeResult`. Callers
+    /// should gate feature requests (hover, completion, ...) on these rather
+    /// than assuming every server supports everything.
+    fn capabilities(&self) -> Option<&ServerCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// Drains the next queued notification (diagnostics, log message,
+    /// progress, ...) pushed by the server, if any. Returns `None` if the
+    /// server hasn't sent one or the transport isn't running.
+    fn next_message(&self) -> Option<ServerMessage> {
+        self.transport.as_ref()?.try_recv_message()
+    }
+
+    /// Blocks until the server pushes a notification (diagnostics, log
+    /// message, progress, ...). Returns `None` if the transport isn't
+    /// running, or once it has shut down and will never send again.
+    fn wait_message(&self) -> Option<ServerMessage> {
+        self.transport.as_ref()?.recv_message()
+    }
+
     fn initialize_lsp(&mut self) -> LSPResult {
         self.started()?;
         let request = LSPProtocol::initialize_request(&self.workspace);
-        let mut req_ser =
-            serde_json::to_string(&request).map_err(|e| LSPError::JSONSerializationError(e))?;
-        req_ser = format!("Content-Length: {}\r\n{}", req_ser.len(), req_ser);
-        let proc = self.process_mut()?;
-        let stdin = proc
-            .stdin
-            .as_mut()
-            .ok_or_else(|| LSPError::Other("stdin is unavailable"))?;
-
-        let stdout: &mut ChildStdout = proc
-            .stdout
-            .as_mut()
-            .ok_or_else(|| LSPError::Other("stdout is unavailable"))?;
-
-        stdin
-            .write_all(req_ser.as_bytes())
-            .map_err(|_| LSPError::Other("Failed to write to LSP"))?;
-        stdin.flush();
-        let mut output = String::new();
-        /// TODO: Find a way to read async. I want to manage messages from the LSP server in a
-        /// non-blocking manner, this can be hacked manually through the RawFd but that would be
-        /// a bad way
-        let mut reader = BufReader::new(stdout);
-        reader.read_line(&mut output).unwrap();
-        /* let res: InitializeResult = serde_json::from_reader(stdout)
-        .map_err(|_| LSPError::Other("Failed to decode JSON from LSP Server"))?; */
-        error!("Initialize LSP Response: {:?}", output);
+        let result: InitializeResult = transport::block_on(
+            self.transport()?
+                .request::<lsp_types::request::Initialize>(request),
+        )?;
+        debug!("Initialize LSP Response: {:?}", result);
+        self.capabilities = Some(result.capabilities);
+        self.transport()?
+            .notify::<lsp_types::notification::Initialized>(InitializedParams {})?;
         Ok(())
     }
 
@@ -215,28 +275,79 @@ impl LSPServer {
         if self.started {
             return Err(LSPStartError::AlreadyStarted.into());
         }
-        let prog = self.language.program();
-        match Command::new(prog)
+        let mut proc = Command::new(&self.config.command)
+            .args(&self.config.args)
+            .envs(self.config.env.iter().cloned())
             .stdout(Stdio::piped())
             .stdin(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
-        {
-            Ok(proc) => {
-                self.started = true;
-                self.process = Some(proc);
-                Ok(())
-            }
-            Err(e) => Err(LSPStartError::SpawnFailed(e).into()),
-        }
+            .map_err(LSPStartError::SpawnFailed)?;
+
+        let stdin = proc
+            .stdin
+            .take()
+            .ok_or_else(|| LSPError::Other("stdin is unavailable"))?;
+        let stdout = proc
+            .stdout
+            .take()
+            .ok_or_else(|| LSPError::Other("stdout is unavailable"))?;
+
+        self.transport = Some(Transport::new(BufReader::new(stdout), stdin));
+        self.started = true;
+        self.process = Some(proc);
+        Ok(())
     }
 
     fn stop(&mut self) -> LSPResult {
         self.started()?;
-        self.process_mut()?
-            .kill()
-            .map_err(|e| LSPError::StopError(LSPStopError::FailedToKill(e)))?;
-        self.process = None;
+        // A server started via `with_transport` has no local child to kill;
+        // the caller owns that pipe's lifecycle.
+        if let Some(mut proc) = self.process.take() {
+            proc.kill()
+                .map_err(|e| LSPError::StopError(LSPStopError::FailedToKill(e)))?;
+        }
+        self.transport = None;
+        self.capabilities = None;
+        self.started = false;
+        Ok(())
+    }
+
+    /// Performs the graceful LSP shutdown handshake: `shutdown` request,
+    /// wait for its (empty) response, then `exit` notification. A locally
+    /// spawned process is only reaped after that, falling back to `kill()`
+    /// if it doesn't exit within `SHUTDOWN_TIMEOUT`; a server started via
+    /// `with_transport` has no such process to reap.
+    fn shutdown(&mut self) -> LSPResult {
+        self.started()?;
+        transport::block_on(
+            self.transport()?
+                .request::<lsp_types::request::Shutdown>(()),
+        )?;
+        self.transport()?
+            .notify::<lsp_types::notification::Exit>(())?;
+
+        if let Some(mut proc) = self.process.take() {
+            let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+            loop {
+                if proc
+                    .try_wait()
+                    .map_err(|e| LSPError::StopError(LSPStopError::FailedToKill(e)))?
+                    .is_some()
+                {
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    proc.kill()
+                        .map_err(|e| LSPError::StopError(LSPStopError::FailedToKill(e)))?;
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+
+        self.transport = None;
+        self.capabilities = None;
         self.started = false;
         Ok(())
     }
@@ -258,7 +369,11 @@ mod test {
     #[test]
     fn test_sanity_lsp_server_start_stop() {
         setup();
-        let mut lsp_server = LSPServer::new(Language::Rust, ".".into());
+        let mut lsp_server = LSPServer::new(
+            Language::Rust,
+            ".".into(),
+            default_server_config(&Language::Rust),
+        );
         lsp_server.start().unwrap();
         lsp_server.stop().unwrap();
     }
@@ -266,7 +381,11 @@ mod test {
     #[test]
     fn test_sanity_lsp_server_start_restart() {
         setup();
-        let mut lsp_server = LSPServer::new(Language::Rust, ".".into());
+        let mut lsp_server = LSPServer::new(
+            Language::Rust,
+            ".".into(),
+            default_server_config(&Language::Rust),
+        );
         lsp_server.start().unwrap();
         lsp_server.restart().unwrap();
     }
@@ -274,8 +393,25 @@ mod test {
     #[test]
     fn test_lsp_initialize() {
         setup();
-        let mut lsp_server = LSPServer::new(Language::Rust, ".".into());
+        let mut lsp_server = LSPServer::new(
+            Language::Rust,
+            ".".into(),
+            default_server_config(&Language::Rust),
+        );
+        lsp_server.start().unwrap();
+        lsp_server.initialize_lsp().unwrap();
+    }
+
+    #[test]
+    fn test_lsp_shutdown() {
+        setup();
+        let mut lsp_server = LSPServer::new(
+            Language::Rust,
+            ".".into(),
+            default_server_config(&Language::Rust),
+        );
         lsp_server.start().unwrap();
         lsp_server.initialize_lsp().unwrap();
+        lsp_server.shutdown().unwrap();
     }
 }
@@ -0,0 +1,104 @@
+use {
+    lsp_types::{LogMessageParams, ProgressParams, PublishDiagnosticsParams},
+    serde_json::Value,
+};
+
+/// A notification pushed unsolicited by the server (no response expected),
+/// classified by method and deserialized into its `lsp_types` payload where
+/// we recognize it.
+#[derive(Debug)]
+pub enum ServerMessage {
+    Diagnostics(PublishDiagnosticsParams),
+    Log(LogMessageParams),
+    Progress(ProgressParams),
+    /// A notification method we don't have a typed payload for, or a
+    /// recognized method whose params failed to deserialize into its payload.
+    Unknown {
+        method: String,
+        params: Option<Value>,
+    },
+}
+
+impl ServerMessage {
+    pub(crate) fn from_notification(method: String, params: Option<Value>) -> Self {
+        match method.as_str() {
+            "textDocument/publishDiagnostics" => {
+                if let Some(Ok(p)) = params.clone().map(serde_json::from_value) {
+                    return ServerMessage::Diagnostics(p);
+                }
+            }
+            "window/logMessage" => {
+                if let Some(Ok(p)) = params.clone().map(serde_json::from_value) {
+                    return ServerMessage::Log(p);
+                }
+            }
+            "$/progress" => {
+                if let Some(Ok(p)) = params.clone().map(serde_json::from_value) {
+                    return ServerMessage::Progress(p);
+                }
+            }
+            _ => {}
+        }
+        ServerMessage::Unknown { method, params }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_notification_diagnostics() {
+        let params = json!({
+            "uri": "file:///tmp/foo.rs",
+            "diagnostics": [],
+        });
+        let message = ServerMessage::from_notification(
+            "textDocument/publishDiagnostics".to_owned(),
+            Some(params),
+        );
+        assert!(matches!(message, ServerMessage::Diagnostics(_)));
+    }
+
+    #[test]
+    fn test_from_notification_log() {
+        let params = json!({ "type": 3, "message": "hello" });
+        let message =
+            ServerMessage::from_notification("window/logMessage".to_owned(), Some(params));
+        assert!(matches!(message, ServerMessage::Log(_)));
+    }
+
+    #[test]
+    fn test_from_notification_progress() {
+        let params = json!({ "token": "1", "value": { "kind": "begin", "title": "indexing" } });
+        let message = ServerMessage::from_notification("$/progress".to_owned(), Some(params));
+        assert!(matches!(message, ServerMessage::Progress(_)));
+    }
+
+    #[test]
+    fn test_from_notification_unknown_method() {
+        let params = json!({ "foo": "bar" });
+        let message = ServerMessage::from_notification(
+            "custom/somethingWeDontKnow".to_owned(),
+            Some(params.clone()),
+        );
+        match message {
+            ServerMessage::Unknown { method, params: p } => {
+                assert_eq!(method, "custom/somethingWeDontKnow");
+                assert_eq!(p, Some(params));
+            }
+            other => panic!("expected Unknown, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_notification_recognized_method_bad_payload_falls_back_to_unknown() {
+        let params = json!({ "not": "a diagnostics payload" });
+        let message = ServerMessage::from_notification(
+            "textDocument/publishDiagnostics".to_owned(),
+            Some(params),
+        );
+        assert!(matches!(message, ServerMessage::Unknown { .. }));
+    }
+}
@@ -0,0 +1,353 @@
+use {
+    crate::framing,
+    serde_json::Value,
+    std::io::{self, BufReader, Read, Write},
+};
+
+/// Bidirectional mapping between the workspace root the client sees and the
+/// root the language server actually runs against, used to rewrite `file://`
+/// URIs *and* bare filesystem paths (e.g. `InitializeParams::root_path`)
+/// crossing the wire in either direction. The `file://` root is derived from
+/// the path root rather than taken as a second, independently-configured
+/// value, so the two forms can never drift apart and both get rewritten
+/// consistently regardless of which one a given server/request uses.
+#[derive(Debug, Clone)]
+pub struct UriMap {
+    local_path_root: String,
+    remote_path_root: String,
+    local_uri_root: String,
+    remote_uri_root: String,
+}
+
+impl UriMap {
+    /// `local_root`/`remote_root` are plain filesystem paths, e.g.
+    /// `/home/alice/project`.
+    pub fn new(local_root: impl Into<String>, remote_root: impl Into<String>) -> Self {
+        let local_path_root = local_root.into();
+        let remote_path_root = remote_root.into();
+        let local_uri_root = format!("file://{}", local_path_root);
+        let remote_uri_root = format!("file://{}", remote_path_root);
+        Self {
+            local_path_root,
+            remote_path_root,
+            local_uri_root,
+            remote_uri_root,
+        }
+    }
+
+    fn to_remote(&self, uri: &str) -> String {
+        rewrite_root(uri, &self.local_uri_root, &self.remote_uri_root)
+    }
+
+    fn to_local(&self, uri: &str) -> String {
+        rewrite_root(uri, &self.remote_uri_root, &self.local_uri_root)
+    }
+
+    fn path_to_remote(&self, path: &str) -> String {
+        rewrite_root(path, &self.local_path_root, &self.remote_path_root)
+    }
+
+    fn path_to_local(&self, path: &str) -> String {
+        rewrite_root(path, &self.remote_path_root, &self.local_path_root)
+    }
+}
+
+/// Rewrites `uri`'s `from` root to `to`, but only when `from` is a genuine
+/// path-component prefix (the remainder is empty or starts with `/`) —
+/// otherwise a sibling that merely shares `from` as a string prefix (e.g.
+/// `/home/alice/project-tools` under root `/home/alice/project`) would be
+/// corrupted rather than left untouched. rust-analyzer routinely reports
+/// paths outside the project root (dependency/std sources), so this case
+/// comes up in practice, not just in theory.
+fn rewrite_root(uri: &str, from: &str, to: &str) -> String {
+    match uri.strip_prefix(from) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", to, rest),
+        _ => uri.to_owned(),
+    }
+}
+
+/// Which direction a rewrite pass runs: incoming server messages go
+/// remote-to-local, outgoing client messages go local-to-remote.
+#[derive(Clone, Copy)]
+enum Direction {
+    ToLocal,
+    ToRemote,
+}
+
+/// Fields that carry a `file://` URI somewhere in the LSP wire format,
+/// including nested occurrences inside diagnostics, locations, references,
+/// and file rename/create/delete resource operations. `rootPath` carries a
+/// bare filesystem path instead and is rewritten separately; `changes` is a
+/// `WorkspaceEdit` map whose *keys* (not values) are URIs.
+const URI_FIELDS: &[&str] = &["uri", "rootUri", "targetUri", "oldUri", "newUri"];
+
+fn rewrite_uris(value: &mut Value, map: &UriMap, dir: Direction) {
+    match value {
+        Value::Object(obj) => {
+            if let Some(changes) = obj.get_mut("changes") {
+                rewrite_change_keys(changes, map, dir);
+            }
+            for (key, v) in obj.iter_mut() {
+                if key == "changes" {
+                    continue;
+                }
+                if let Value::String(s) = v {
+                    if key == "rootPath" {
+                        *s = match dir {
+                            Direction::ToLocal => map.path_to_local(s),
+                            Direction::ToRemote => map.path_to_remote(s),
+                        };
+                        continue;
+                    }
+                    if URI_FIELDS.contains(&key.as_str()) {
+                        *s = match dir {
+                            Direction::ToLocal => map.to_local(s),
+                            Direction::ToRemote => map.to_remote(s),
+                        };
+                        continue;
+                    }
+                }
+                rewrite_uris(v, map, dir);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite_uris(item, map, dir);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Rewrites the URI *keys* of a `WorkspaceEdit.changes` map (`{uri:
+/// TextEdit[]}`), since `rewrite_uris` only ever touches object values.
+fn rewrite_change_keys(value: &mut Value, map: &UriMap, dir: Direction) {
+    if let Value::Object(changes) = value {
+        let rewritten = std::mem::take(changes)
+            .into_iter()
+            .map(|(uri, mut edits)| {
+                rewrite_uris(&mut edits, map, dir);
+                let uri = match dir {
+                    Direction::ToLocal => map.to_local(&uri),
+                    Direction::ToRemote => map.to_remote(&uri),
+                };
+                (uri, edits)
+            })
+            .collect();
+        *changes = rewritten;
+    }
+}
+
+fn to_io_error<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Wraps a remote `Read` half, rewriting every LSP message's URIs from the
+/// remote root back to the local one before the framed bytes ever reach
+/// `Transport`. Since rewriting changes the body length, each message is
+/// re-framed with a recomputed `Content-Length`.
+pub struct RewritingReader<R: Read> {
+    inner: BufReader<R>,
+    uri_map: UriMap,
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> RewritingReader<R> {
+    pub fn new(inner: R, uri_map: UriMap) -> Self {
+        Self {
+            inner: BufReader::new(inner),
+            uri_map,
+            pending: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut value: Value = framing::read_message(&mut self.inner).map_err(to_io_error)?;
+        rewrite_uris(&mut value, &self.uri_map, Direction::ToLocal);
+        let rewritten = serde_json::to_vec(&value).map_err(to_io_error)?;
+
+        self.pending = format!("Content-Length: {}\r\n\r\n", rewritten.len()).into_bytes();
+        self.pending.extend_from_slice(&rewritten);
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for RewritingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.pending.len() {
+            self.fill()?;
+        }
+        let remaining = &self.pending[self.pos..];
+        let n = remaining.len().min(buf.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Wraps a remote `Write` half, rewriting a fully-framed outgoing message's
+/// URIs from the local root to the remote one and re-framing it with a
+/// recomputed `Content-Length`. Relies on `Transport` always handing a
+/// complete `Content-Length` header plus body to a single `write_all` call.
+pub struct RewritingWriter<W: Write> {
+    inner: W,
+    uri_map: UriMap,
+}
+
+impl<W: Write> RewritingWriter<W> {
+    pub fn new(inner: W, uri_map: UriMap) -> Self {
+        Self { inner, uri_map }
+    }
+}
+
+impl<W: Write> Write for RewritingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, frame: &[u8]) -> io::Result<()> {
+        let body = framing::split_frame(frame).map_err(to_io_error)?;
+        let mut value: Value = serde_json::from_slice(body).map_err(to_io_error)?;
+        rewrite_uris(&mut value, &self.uri_map, Direction::ToRemote);
+        let rewritten = serde_json::to_vec(&value).map_err(to_io_error)?;
+
+        let mut framed = format!("Content-Length: {}\r\n\r\n", rewritten.len()).into_bytes();
+        framed.extend_from_slice(&rewritten);
+        self.inner.write_all(&framed)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn uri_map() -> UriMap {
+        UriMap::new("/home/alice/project", "/workspace")
+    }
+
+    #[test]
+    fn test_to_remote_and_to_local_roundtrip() {
+        let map = uri_map();
+        let remote = map.to_remote("file:///home/alice/project/src/main.rs");
+        assert_eq!(remote, "file:///workspace/src/main.rs");
+        assert_eq!(
+            map.to_local(&remote),
+            "file:///home/alice/project/src/main.rs"
+        );
+    }
+
+    #[test]
+    fn test_to_remote_leaves_unrelated_uri_untouched() {
+        let map = uri_map();
+        let uri = "file:///etc/hosts";
+        assert_eq!(map.to_remote(uri), uri);
+    }
+
+    #[test]
+    fn test_to_remote_leaves_sibling_with_shared_string_prefix_untouched() {
+        let map = uri_map();
+        let uri = "file:///home/alice/project-tools/x.rs";
+        assert_eq!(map.to_remote(uri), uri);
+    }
+
+    #[test]
+    fn test_path_to_remote_leaves_sibling_with_shared_string_prefix_untouched() {
+        let map = uri_map();
+        let path = "/home/alice/project-tools/x.rs";
+        assert_eq!(map.path_to_remote(path), path);
+    }
+
+    #[test]
+    fn test_path_to_remote_and_to_local_roundtrip() {
+        let map = uri_map();
+        let remote = map.path_to_remote("/home/alice/project/src/main.rs");
+        assert_eq!(remote, "/workspace/src/main.rs");
+        assert_eq!(
+            map.path_to_local(&remote),
+            "/home/alice/project/src/main.rs"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_uris_rewrites_root_path_via_path_form() {
+        let map = uri_map();
+        let mut value = json!({
+            "rootPath": "/home/alice/project",
+            "rootUri": "file:///home/alice/project",
+        });
+        rewrite_uris(&mut value, &map, Direction::ToRemote);
+        assert_eq!(value["rootPath"], "/workspace");
+        assert_eq!(value["rootUri"], "file:///workspace");
+    }
+
+    #[test]
+    fn test_rewrite_uris_nested_fields() {
+        let map = uri_map();
+        let mut value = json!({
+            "uri": "file:///home/alice/project/src/main.rs",
+            "diagnostics": [
+                { "range": {}, "relatedInformation": [
+                    { "location": { "uri": "file:///home/alice/project/src/lib.rs" } }
+                ] }
+            ],
+        });
+        rewrite_uris(&mut value, &map, Direction::ToRemote);
+        assert_eq!(value["uri"], "file:///workspace/src/main.rs");
+        assert_eq!(
+            value["diagnostics"][0]["relatedInformation"][0]["location"]["uri"],
+            "file:///workspace/src/lib.rs"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_uris_rename_fields() {
+        let map = uri_map();
+        let mut value = json!({
+            "oldUri": "file:///home/alice/project/old.rs",
+            "newUri": "file:///home/alice/project/new.rs",
+        });
+        rewrite_uris(&mut value, &map, Direction::ToRemote);
+        assert_eq!(value["oldUri"], "file:///workspace/old.rs");
+        assert_eq!(value["newUri"], "file:///workspace/new.rs");
+    }
+
+    #[test]
+    fn test_rewrite_uris_changes_map_keys_and_values() {
+        let map = uri_map();
+        let mut value = json!({
+            "changes": {
+                "file:///home/alice/project/src/main.rs": [
+                    { "range": {}, "newText": "" }
+                ]
+            }
+        });
+        rewrite_uris(&mut value, &map, Direction::ToRemote);
+        let changes = value["changes"].as_object().unwrap();
+        assert!(changes.contains_key("file:///workspace/src/main.rs"));
+        assert!(!changes.contains_key("file:///home/alice/project/src/main.rs"));
+        assert_eq!(
+            changes["file:///workspace/src/main.rs"],
+            json!([{ "range": {}, "newText": "" }])
+        );
+    }
+
+    #[test]
+    fn test_rewrite_uris_to_local_direction() {
+        let map = uri_map();
+        let mut value = json!({ "uri": "file:///workspace/src/main.rs" });
+        rewrite_uris(&mut value, &map, Direction::ToLocal);
+        assert_eq!(value["uri"], "file:///home/alice/project/src/main.rs");
+    }
+}
@@ -0,0 +1,268 @@
+use {
+    crate::{framing, jsonrpc, messages::ServerMessage, LSPError},
+    lsp_types::request::Request as LSPRequest,
+    serde::Serialize,
+    serde_json::Value,
+    std::{
+        collections::HashMap,
+        future::Future,
+        io::{BufRead, Write},
+        marker::PhantomData,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+        thread::{self, Thread},
+    },
+};
+
+struct Slot {
+    result: Option<Result<Value, LSPError>>,
+    waker: Option<Waker>,
+}
+
+type PendingMap = Arc<Mutex<HashMap<u64, Arc<Mutex<Slot>>>>>;
+
+/// Resolves once the background reader matches a response to this request's
+/// id, deserialized into `R::Result`.
+pub struct ResponseFuture<R: LSPRequest> {
+    slot: Arc<Mutex<Slot>>,
+    _marker: PhantomData<R>,
+}
+
+impl<R: LSPRequest> Future for ResponseFuture<R> {
+    type Output = Result<R::Result, LSPError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(Ok(value)) => {
+                Poll::Ready(serde_json::from_value(value).map_err(LSPError::JSONSerializationError))
+            }
+            Some(Err(e)) => Poll::Ready(Err(e)),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Owns the transport to a language server: a writer half used to send
+/// requests/notifications, and a background thread that reads frames off the
+/// reader half and correlates them to pending requests.
+pub struct Transport {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    request_counter: AtomicU64,
+    pending: PendingMap,
+    incoming_rx: Mutex<mpsc::Receiver<ServerMessage>>,
+    reader_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Transport {
+    /// Spawns the background reader over `reader`/`writer`, which together
+    /// make up the framed stdio (or remote) pipe to the language server.
+    pub fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: BufRead + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let writer: Arc<Mutex<Box<dyn Write + Send>>> = Arc::new(Mutex::new(Box::new(writer)));
+        let pending: PendingMap = Arc::new(Mutex::new(HashMap::new()));
+        let (incoming_tx, incoming_rx) = mpsc::channel();
+
+        let reader_thread = {
+            let pending = pending.clone();
+            let writer = writer.clone();
+            thread::spawn(move || Self::read_loop(reader, writer, pending, incoming_tx))
+        };
+
+        Self {
+            writer,
+            request_counter: AtomicU64::new(0),
+            pending,
+            incoming_rx: Mutex::new(incoming_rx),
+            reader_thread: Some(reader_thread),
+        }
+    }
+
+    /// Sends a typed LSP request and returns a future that resolves to its
+    /// result once the matching response arrives.
+    pub fn request<R: LSPRequest>(&self, params: R::Params) -> ResponseFuture<R> {
+        let id = self.request_counter.fetch_add(1, Ordering::SeqCst);
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }));
+        self.pending.lock().unwrap().insert(id, slot.clone());
+
+        if let Err(e) = self.send_frame(&jsonrpc::Request::new(id, R::METHOD, params)) {
+            slot.lock().unwrap().result = Some(Err(e));
+        }
+
+        ResponseFuture {
+            slot,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sends a typed LSP notification; there is no response to correlate.
+    pub fn notify<N: lsp_types::notification::Notification>(
+        &self,
+        params: N::Params,
+    ) -> Result<(), LSPError> {
+        self.send_frame(&jsonrpc::Notification::new(N::METHOD, params))
+    }
+
+    /// Pulls the next notification forwarded by the background reader
+    /// (diagnostics, log messages, progress, ...), if any are queued.
+    pub(crate) fn try_recv_message(&self) -> Option<ServerMessage> {
+        self.incoming_rx.lock().unwrap().try_recv().ok()
+    }
+
+    /// Blocks until the background reader forwards the next notification
+    /// (diagnostics, log messages, progress, ...). Returns `None` once the
+    /// reader thread has exited and will never send again.
+    pub(crate) fn recv_message(&self) -> Option<ServerMessage> {
+        self.incoming_rx.lock().unwrap().recv().ok()
+    }
+
+    fn send_frame<T: Serialize>(&self, message: &T) -> Result<(), LSPError> {
+        let body = serde_json::to_vec(message).map_err(LSPError::JSONSerializationError)?;
+        let mut frame = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+        frame.extend_from_slice(&body);
+
+        let mut writer = self.writer.lock().unwrap();
+        writer
+            .write_all(&frame)
+            .and_then(|_| writer.flush())
+            .map_err(|e| LSPError::Protocol(format!("failed to write message: {}", e)))
+    }
+
+    fn read_loop<R: BufRead>(
+        mut reader: R,
+        writer: Arc<Mutex<Box<dyn Write + Send>>>,
+        pending: PendingMap,
+        incoming_tx: mpsc::Sender<ServerMessage>,
+    ) {
+        loop {
+            let incoming: jsonrpc::Incoming = match framing::read_message(&mut reader) {
+                Ok(incoming) => incoming,
+                Err(LSPError::JSONSerializationError(e)) => {
+                    warn!("dropping malformed LSP message: {}", e);
+                    continue;
+                }
+                Err(e) => {
+                    debug!("LSP transport reader exiting: {}", e);
+                    return;
+                }
+            };
+
+            match incoming.classify() {
+                jsonrpc::Shape::Response { id, result, error } => {
+                    // The id may be a string if a non-conforming peer echoes
+                    // one back; `Transport` only ever mints numeric ids for
+                    // its own requests, so there's nothing to correlate a
+                    // string id against.
+                    let id = match id.as_u64() {
+                        Some(id) => id,
+                        None => {
+                            warn!("dropping response with non-numeric id {:?}", id);
+                            continue;
+                        }
+                    };
+                    let slot = pending.lock().unwrap().remove(&id);
+                    if let Some(slot) = slot {
+                        let mut slot = slot.lock().unwrap();
+                        slot.result = Some(match error {
+                            Some(e) => Err(LSPError::Protocol(format!(
+                                "server returned error {}: {}",
+                                e.code, e.message
+                            ))),
+                            None => Ok(result.unwrap_or(Value::Null)),
+                        });
+                        if let Some(waker) = slot.waker.take() {
+                            waker.wake();
+                        }
+                    } else {
+                        warn!("response for unknown request id {}", id);
+                    }
+                }
+                jsonrpc::Shape::Notification { method, params } => {
+                    let _ = incoming_tx.send(ServerMessage::from_notification(method, params));
+                }
+                jsonrpc::Shape::Call { id, method, .. } => {
+                    warn!(
+                        "server call {:?} is unsupported, answering with an error",
+                        method
+                    );
+                    let response = jsonrpc::Response::error(
+                        id,
+                        jsonrpc::METHOD_NOT_FOUND,
+                        format!("method not found: {}", method),
+                    );
+                    if let Ok(body) = serde_json::to_vec(&response) {
+                        let mut frame =
+                            format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+                        frame.extend_from_slice(&body);
+                        let mut writer = writer.lock().unwrap();
+                        let _ = writer.write_all(&frame).and_then(|_| writer.flush());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Builds a `Waker` that unparks the calling thread, since there's no async
+/// executor in this crate to drive `ResponseFuture`s.
+fn thread_waker() -> Waker {
+    fn clone(ptr: *const ()) -> RawWaker {
+        let thread = unsafe { Arc::from_raw(ptr as *const Thread) };
+        let cloned = thread.clone();
+        std::mem::forget(thread);
+        RawWaker::new(Arc::into_raw(cloned) as *const (), &VTABLE)
+    }
+    fn wake(ptr: *const ()) {
+        let thread = unsafe { Arc::from_raw(ptr as *const Thread) };
+        thread.unpark();
+    }
+    fn wake_by_ref(ptr: *const ()) {
+        let thread = unsafe { &*(ptr as *const Thread) };
+        thread.unpark();
+    }
+    fn drop_raw(ptr: *const ()) {
+        unsafe { drop(Arc::from_raw(ptr as *const Thread)) };
+    }
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop_raw);
+
+    let thread = Arc::new(thread::current());
+    let raw = RawWaker::new(Arc::into_raw(thread) as *const (), &VTABLE);
+    unsafe { Waker::from_raw(raw) }
+}
+
+/// Drives a `ResponseFuture` to completion on the current thread, parking it
+/// between polls until the background reader wakes it.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = thread_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+impl Drop for Transport {
+    fn drop(&mut self) {
+        if let Some(handle) = self.reader_thread.take() {
+            // The reader thread exits on its own once the pipe closes; we
+            // just avoid leaking the handle.
+            drop(handle);
+        }
+    }
+}